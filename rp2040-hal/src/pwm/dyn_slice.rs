@@ -1,6 +1,7 @@
 //! Semi-internal enums mostly used in typelevel magic
 
 use embedded_hal::PwmPin;
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
 
 use super::{reg::RegisterInterface, Slice, SliceId, SliceMode, ValidSliceMode, Channel, ChannelId};
 use crate::{atomic_register_access::{write_bitmask_clear, write_bitmask_set}, gpio::DynPin};
@@ -55,6 +56,7 @@ impl DynSliceRegisters {
 pub struct DynSlice {
     regs: DynSliceRegisters,
     mode: DynSliceMode,
+    ph_correct: bool,
 }
 
 impl DynSlice {
@@ -66,6 +68,7 @@ impl DynSlice {
         DynSlice {
             regs: DynSliceRegisters::new(id),
             mode,
+            ph_correct: false,
         }
     }
 
@@ -83,6 +86,7 @@ impl DynSlice {
     /// Set a default config for the slice
     pub fn default_config(&mut self) {
         self.regs.write_ph_correct(false);
+        self.ph_correct = false;
         self.regs.write_div_int(1); // No divisor
         self.regs.write_div_frac(0); // No divisor
         self.regs.write_inv_a(false); //Don't invert the channel
@@ -112,13 +116,15 @@ impl DynSlice {
     /// Enable phase correct mode
     #[inline]
     pub fn set_ph_correct(&mut self) {
-        self.regs.write_ph_correct(true)
+        self.regs.write_ph_correct(true);
+        self.ph_correct = true;
     }
 
     /// Disables phase correct mode
     #[inline]
     pub fn clr_ph_correct(&mut self) {
-        self.regs.write_ph_correct(false)
+        self.regs.write_ph_correct(false);
+        self.ph_correct = false;
     }
 
     /// Enable slice
@@ -169,6 +175,58 @@ impl DynSlice {
         self.regs.write_top(value)
     }
 
+    /// Configure the clock divider and `TOP` to target a PWM frequency, instead of
+    /// hand-computing register values.
+    pub fn set_frequency(
+        &mut self,
+        target_hz: u32,
+        sys_clk_hz: u32,
+    ) -> Result<ConfiguredFreq, PwmFreqError> {
+        if target_hz == 0 {
+            return Err(PwmFreqError::TooLow);
+        }
+
+        // In phase-correct mode the counter runs up to `top` then back down to 0, so one
+        // period is `2 * (top + 1)` counts instead of `top + 1`.
+        let period_multiplier: u64 = if self.ph_correct { 2 } else { 1 };
+
+        // Smallest divider, in 12.4 fixed point (`divider_16 == 16` is a divider of 1.0),
+        // that keeps `top` within the 16-bit TOP register, solved for directly rather than
+        // searched one 1/16th step at a time (the Cortex-M0+ has no hardware divider, so a
+        // fine-grained search here would be expensive). `top <= 0xffff` requires
+        // `count_rate <= denom`, i.e. `divider_16 > sys_clk_hz * 16 / (denom + 1)`.
+        let denom = target_hz as u64 * 0x10000 * period_multiplier;
+        let divider_16 = (sys_clk_hz as u64 * 16 / (denom + 1) + 1).max(16);
+        if divider_16 > 255 * 16 + 15 {
+            return Err(PwmFreqError::TooLow);
+        }
+        let div_int = (divider_16 / 16) as u8;
+        let div_frac = (divider_16 % 16) as u8;
+
+        let count_rate = sys_clk_hz as u64 * 16 / divider_16;
+        let periods = count_rate / target_hz as u64;
+        if periods < 2 * period_multiplier {
+            return Err(PwmFreqError::TooHigh);
+        }
+        let top = (periods / period_multiplier - 1) as u16;
+
+        self.regs.write_div_int(div_int);
+        self.regs.write_div_frac(div_frac);
+        self.regs.write_top(top);
+
+        let divider = div_int as f32 + div_frac as f32 / 16.0;
+        let freq_hz = (sys_clk_hz as f32
+            / divider
+            / (top as f32 + 1.0)
+            / period_multiplier as f32) as u32;
+        let resolution_bits = 32 - (top as u32).leading_zeros();
+
+        Ok(ConfiguredFreq {
+            freq_hz,
+            resolution_bits,
+        })
+    }
+
     /// Create the interrupt bitmask corresponding to this slice
     #[inline]
     fn bitmask(&self) -> u32 {
@@ -231,6 +289,26 @@ impl DynSlice {
     }
 }
 
+/// The result of a successful [`DynSlice::set_frequency`] call
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ConfiguredFreq {
+    /// The frequency actually achieved, in Hz
+    pub freq_hz: u32,
+    /// The number of bits of duty-cycle resolution available at this frequency (the number
+    /// of bits needed to represent `0..=top`)
+    pub resolution_bits: u32,
+}
+
+/// Error returned by [`DynSlice::set_frequency`] when `target_hz` cannot be reached
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PwmFreqError {
+    /// The target frequency is higher than the system clock can produce even with `top == 1`
+    TooHigh,
+    /// The target frequency is lower than the system clock can produce even at the maximum
+    /// divider
+    TooLow,
+}
+
 impl<I, M> From<Slice<I, M>> for DynSlice
 where
     I: SliceId,
@@ -242,6 +320,240 @@ where
     }
 }
 
+/// One step of a duty-cycle sequence: the register values to load for a single frame.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct Frame {
+    /// Channel A compare value for this frame
+    pub cc_a: u16,
+    /// Channel B compare value for this frame
+    pub cc_b: u16,
+    /// Wrap (`TOP`) value for this frame
+    pub top: u16,
+    /// Integer part of the clock divider for this frame
+    pub div_int: u8,
+    /// Fractional part of the clock divider for this frame
+    pub div_frac: u8,
+}
+
+/// How many times a [`SequencePlayer`] repeats its sequence before stopping
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum RepeatMode {
+    /// Play the sequence once, then disable the slice
+    OneShot,
+    /// Play the sequence the given number of times, then disable the slice
+    Times(u32),
+    /// Play the sequence forever
+    Loop,
+}
+
+/// Drives a [`DynSlice`] through a buffer of [`Frame`]s on every wrap interrupt, without DMA.
+pub struct SequencePlayer<'a> {
+    slice: DynSlice,
+    frames: &'a [Frame],
+    index: usize,
+    repeat: RepeatMode,
+    remaining: u32,
+}
+
+impl<'a> SequencePlayer<'a> {
+    /// Create a new sequence player driving `slice` through `frames` according to `repeat`.
+    pub fn new(slice: DynSlice, frames: &'a [Frame], repeat: RepeatMode) -> Self {
+        let remaining = match repeat {
+            RepeatMode::Times(n) => n,
+            RepeatMode::OneShot | RepeatMode::Loop => 0,
+        };
+
+        SequencePlayer {
+            slice,
+            frames,
+            index: 0,
+            repeat,
+            remaining,
+        }
+    }
+
+    #[inline]
+    fn load_frame(&mut self, frame: Frame) {
+        self.slice.regs.write_cc_a(frame.cc_a);
+        self.slice.regs.write_cc_b(frame.cc_b);
+        self.slice.regs.write_top(frame.top);
+        self.slice.regs.write_div_int(frame.div_int);
+        self.slice.regs.write_div_frac(frame.div_frac);
+    }
+
+    /// Load the first frame and start playback.
+    ///
+    /// Panics if `frames` is empty. Does nothing if `repeat` is `Times(0)`.
+    pub fn start(&mut self) {
+        if matches!(self.repeat, RepeatMode::Times(0)) {
+            return;
+        }
+
+        self.index = 0;
+        let frame = self.frames[0];
+        self.load_frame(frame);
+        self.slice.enable_interrupt();
+        self.slice.enable();
+    }
+
+    /// Advance to the next frame. Call this from the `PWM_IRQ_WRAP` handler.
+    ///
+    /// Returns `true` once the sequence has completed and the slice has been disabled.
+    pub fn on_wrap(&mut self) -> bool {
+        self.slice.clear_interrupt();
+
+        self.index += 1;
+        if self.index >= self.frames.len() {
+            self.index = 0;
+
+            match self.repeat {
+                RepeatMode::OneShot => return self.finish(),
+                RepeatMode::Times(_) => {
+                    self.remaining = self.remaining.saturating_sub(1);
+                    if self.remaining == 0 {
+                        return self.finish();
+                    }
+                }
+                RepeatMode::Loop => {}
+            }
+        }
+
+        let frame = self.frames[self.index];
+        self.load_frame(frame);
+        false
+    }
+
+    fn finish(&mut self) -> bool {
+        self.slice.disable();
+        self.slice.disable_interrupt();
+        true
+    }
+
+    /// Stop playback immediately and return the underlying slice.
+    pub fn free(mut self) -> DynSlice {
+        self.slice.disable();
+        self.slice.disable_interrupt();
+        self.slice
+    }
+}
+
+/// Error returned by a [`DynInputCapture`] measurement
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CaptureError {
+    /// The 16-bit counter wrapped during the gate interval, so the count is unreliable
+    Overflow,
+    /// `read_frequency` was called before a non-zero gate length was armed with `start_capture`
+    GateNotArmed,
+}
+
+/// Measures the frequency or duty cycle of a signal on a slice's B pin by gating its counting
+/// mode over a known interval and reading back the counter.
+pub struct DynInputCapture {
+    slice: DynSlice,
+    gate_cycles: u32,
+}
+
+impl DynInputCapture {
+    /// Wrap `slice` for input capture.
+    ///
+    /// `slice` must already be configured in `CountRisingEdge`, `CountFallingEdge`, or
+    /// `InputHighRunning` mode with its B pin routed to the signal to be measured.
+    pub fn new(slice: DynSlice) -> Self {
+        DynInputCapture {
+            slice,
+            gate_cycles: 0,
+        }
+    }
+
+    /// Reset the counter and open the gate, recording `gate_cycles` as the gate length in
+    /// system clock cycles.
+    ///
+    /// The gate is not timed here: it stays open until [`Self::read_frequency`] or
+    /// [`Self::read_duty_ratio`] closes it, which the caller does after `gate_cycles` cycles.
+    pub fn start_capture(&mut self, gate_cycles: u32) {
+        self.gate_cycles = gate_cycles;
+        self.slice.disable();
+        self.slice.set_counter(0);
+        self.slice.clear_interrupt();
+        self.slice.enable();
+    }
+
+    fn end_capture(&mut self) -> Result<u16, CaptureError> {
+        self.slice.disable();
+        // Check the raw wrap flag: the masked status behind `has_overflown` stays clear
+        // unless the slice's interrupt is enabled.
+        let mask = self.slice.bitmask();
+        let wrapped = unsafe { (*pac::PWM::ptr()).intr.read().bits() & mask == mask };
+        if wrapped {
+            Err(CaptureError::Overflow)
+        } else {
+            Ok(self.slice.get_counter())
+        }
+    }
+
+    /// Close the gate opened by [`Self::start_capture`] and compute the signal frequency in
+    /// Hz, given the system clock rate the gate was timed against.
+    pub fn read_frequency(&mut self, sys_clk_hz: u32) -> Result<u32, CaptureError> {
+        if self.gate_cycles == 0 {
+            return Err(CaptureError::GateNotArmed);
+        }
+        let count = self.end_capture()?;
+        Ok((count as u64 * sys_clk_hz as u64 / self.gate_cycles as u64) as u32)
+    }
+
+    /// Close the gate opened by [`Self::start_capture`] and compute a duty ratio
+    /// (`0.0..=1.0`) from the high-level count, given `total_count` counted over the same
+    /// window by a separate free-running timebase (e.g. another slice in `FreeRunning` mode).
+    ///
+    /// `slice` must be configured in `InputHighRunning` mode for the result to be meaningful.
+    pub fn read_duty_ratio(&mut self, total_count: u16) -> Result<f32, CaptureError> {
+        let high_count = self.end_capture()?;
+        if total_count == 0 {
+            Ok(0.0)
+        } else {
+            Ok(high_count as f32 / total_count as f32)
+        }
+    }
+
+    /// Consume the capture wrapper, returning the underlying slice.
+    pub fn free(self) -> DynSlice {
+        self.slice
+    }
+}
+
+/// Enables and phase-aligns several [`DynSlice`]s with a single write to the PWM `EN` register.
+#[derive(Default)]
+pub struct DynSliceGroup {
+    mask: u32,
+}
+
+impl DynSliceGroup {
+    /// Create an empty group.
+    pub fn new() -> Self {
+        DynSliceGroup { mask: 0 }
+    }
+
+    /// Add `slice` to the group: disable it and reset its counter to 0, then accumulate its
+    /// bit into the group's mask.
+    ///
+    /// Add every member before calling [`Self::enable`]; a slice added afterwards is not
+    /// covered by the synchronized start.
+    pub fn add(&mut self, slice: &mut DynSlice) {
+        slice.disable();
+        slice.set_counter(0);
+        self.mask |= slice.bitmask();
+    }
+
+    /// Enable every slice in the group with a single write to the PWM `EN` register, so they
+    /// all begin counting on the same clock edge.
+    pub fn enable(&self) {
+        unsafe {
+            let pwm = &(*pac::PWM::ptr());
+            write_bitmask_set(pwm.en.as_ptr(), self.mask);
+        }
+    }
+}
+
 pub struct DynChannel {
     regs: DynSliceRegisters,
     mode: DynSliceMode,
@@ -390,6 +702,28 @@ impl PwmPin for DynChannel {
     }
 }
 
+impl ErrorType for DynChannel {
+    type Error = core::convert::Infallible;
+}
+
+impl SetDutyCycle for DynChannel {
+    #[inline]
+    fn max_duty_cycle(&self) -> u16 {
+        self.regs.read_top()
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.duty_cycle = duty;
+        if self.enabled {
+            match self.channel_id {
+                DynChannelId::A => self.regs.write_cc_a(duty),
+                DynChannelId::B => self.regs.write_cc_b(duty),
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<I, M, C> From<Channel<I, M, C>> for DynChannel
 where
     I: SliceId,